@@ -24,17 +24,19 @@
 //! hash_ring.add_node(&test_node);
 //! hash_ring.remove_node(&test_node);
 //! hash_ring.add_node(&test_node);
-//! let x = hash_ring.get_node(hash(&format!("{}{}", test_node.to_string(), 0.to_string())));
+//! let x = hash_ring.get_node_for(&"user:42");
 //! // x is the node in the form of an Option<T> where T: Clone + ToString + Debug
 //! ```
 
 
 use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
 use std::clone::Clone;
 use std::fmt::Debug;
 use std::string::ToString;
 use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
 
 
 pub fn hash<T: Hash>(value: &T) -> u64 {
@@ -43,20 +45,41 @@ pub fn hash<T: Hash>(value: &T) -> u64 {
     h.finish()
 }
 
-pub struct Ring <T: Clone + ToString + Debug> {
+pub struct Ring <T: Clone + ToString + Debug, S = BuildHasherDefault<DefaultHasher>> {
     num_replicas: usize,
     ring: BTreeMap<u64, T>,
+    weights: HashMap<String, usize>,
+    hash_builder: S,
 }
 
 
 impl <T> Ring<T> where T: Clone + ToString + Debug {
     pub fn new(num_replicas: usize) -> Ring<T> {
+        Ring::with_hasher(num_replicas, BuildHasherDefault::default())
+    }
+}
+
+impl <T, S> Ring<T, S> where T: Clone + ToString + Debug, S: BuildHasher {
+    /// Builds a `Ring` that hashes keys with `build_hasher` instead of the
+    /// default `SipHash`-backed `DefaultHasher`. Use this to swap in a
+    /// faster or better-distributed hasher (e.g. XxHash64), or a `BuildHasher`
+    /// seeded at construction time for reproducible, collision-resistant
+    /// ring placement.
+    pub fn with_hasher(num_replicas: usize, build_hasher: S) -> Ring<T, S> {
         Ring {
             num_replicas: num_replicas,
             ring: BTreeMap::new(),
+            weights: HashMap::new(),
+            hash_builder: build_hasher,
         }
     }
 
+    fn hash<K: Hash>(&self, value: &K) -> u64 {
+        let mut h = self.hash_builder.build_hasher();
+        value.hash(&mut h);
+        h.finish()
+    }
+
     pub fn add_nodes(&mut self, nodes: &[T]) {
         if !nodes.is_empty() {
             for node in nodes.iter() { self.add_node(node); }
@@ -70,27 +93,116 @@ impl <T> Ring<T> where T: Clone + ToString + Debug {
     }
 
     pub fn add_node(&mut self, node: &T) {
-        for i in 0..self.num_replicas {
-            let key = hash(&format!("{}{}", node.to_string(), i.to_string()));
+        self.add_weighted_node(node, 1);
+    }
+
+    /// Adds `node` with `num_replicas * weight` virtual points on the ring
+    /// instead of the flat `num_replicas`, so a higher-capacity node owns
+    /// proportionally more of the keyspace. The weight is remembered so
+    /// `remove_node` can later remove exactly the points this call added.
+    pub fn add_weighted_node(&mut self, node: &T, weight: usize) {
+        for i in 0..self.num_replicas * weight {
+            let key = self.hash(&format!("{}{}", node.to_string(), i.to_string()));
             self.ring.insert(key, node.clone());
         }
+        self.weights.insert(node.to_string(), weight);
     }
 
     pub fn remove_node(&mut self, node: &T) {
         assert!(!self.ring.is_empty());
 
+        let weight = self.weights.remove(&node.to_string()).unwrap_or(1);
+        for i in 0..self.num_replicas * weight {
+            let key = self.hash(&format!("{}{}", node.to_string(), i.to_string()));
+            self.ring.remove(&key);
+        }
+    }
+
+    /// Finds the key of the virtual point just before `key` on the ring,
+    /// wrapping around to the highest key when `key` is the smallest (or
+    /// only) point. Marks the start of the arc that `key` currently owns.
+    fn predecessor(&self, key: u64) -> u64 {
+        self.ring.range(..key).next_back().map(|(&k, _)| k)
+            .or_else(|| self.ring.keys().next_back().cloned())
+            .unwrap_or(key)
+    }
+
+    /// Like `add_node`, but also reports the half-open arc(s) `(start, end]`
+    /// of the hash space whose ownership changed, one per inserted virtual
+    /// point. Operators can use these ranges to migrate just the affected
+    /// keys instead of rehashing the whole dataset after a topology change.
+    pub fn add_node_report(&mut self, node: &T) -> Vec<(u64, u64)> {
+        let mut ranges = Vec::with_capacity(self.num_replicas);
+
         for i in 0..self.num_replicas {
-            let key = hash(&format!("{}{}", node.to_string(), i.to_string()));
+            let key = self.hash(&format!("{}{}", node.to_string(), i.to_string()));
+            let predecessor = self.predecessor(key);
+            self.ring.insert(key, node.clone());
+            ranges.push((predecessor, key));
+        }
+        self.weights.insert(node.to_string(), 1);
+
+        ranges
+    }
+
+    /// Like `remove_node`, but also reports the half-open arc(s) `(start,
+    /// end]` of the hash space whose ownership changed, one per removed
+    /// virtual point, so operators can migrate just those key ranges.
+    pub fn remove_node_report(&mut self, node: &T) -> Vec<(u64, u64)> {
+        assert!(!self.ring.is_empty());
+
+        let weight = self.weights.remove(&node.to_string()).unwrap_or(1);
+        let mut ranges = Vec::with_capacity(self.num_replicas * weight);
+
+        for i in 0..self.num_replicas * weight {
+            let key = self.hash(&format!("{}{}", node.to_string(), i.to_string()));
+            let predecessor = self.predecessor(key);
             self.ring.remove(&key);
+            ranges.push((predecessor, key));
         }
+
+        ranges
     }
 
+    /// Looks up the node owning `key`, where `key` is an already-hashed
+    /// ring position. Most callers should prefer `get_node_for`, which
+    /// hashes the key for you; this is kept for advanced use such as
+    /// iterating the ring at caller-chosen positions.
     pub fn get_node(&self, key: u64) -> Option<&T> {
         assert!(!self.ring.is_empty());
-        let mut keys = self.ring.keys();
-        keys.find(|k| *k >= &key)
-            .and_then(|k| self.ring.get(k))
-            .or(keys.nth(0).and_then(|x| self.ring.get(x)))
+        self.ring.range(key..).next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, node)| node)
+    }
+
+    /// Hashes `key` with the ring's configured hasher and returns the node
+    /// it maps to, e.g. `ring.get_node_for(&"user:42")`. This is the
+    /// documented, ergonomic way to look up a node; it spares callers from
+    /// reconstructing the ring's internal key format themselves.
+    pub fn get_node_for<K: Hash>(&self, key: &K) -> Option<&T> {
+        let hashed = self.hash(key);
+        self.get_node(hashed)
+    }
+
+    /// Walks clockwise from `key` and returns up to `n` distinct physical
+    /// nodes, skipping virtual points that map back to a node already
+    /// chosen. This is the primary + successor-replica set a replicated
+    /// key-value store would place a key's copies on; fewer than `n` nodes
+    /// are returned if the ring holds fewer than `n` physical nodes.
+    pub fn get_nodes(&self, key: u64, n: usize) -> Vec<&T> {
+        let mut seen = HashSet::new();
+        let mut nodes = Vec::new();
+
+        for (_, node) in self.ring.range(key..).chain(self.ring.range(..key)) {
+            if nodes.len() >= n {
+                break;
+            }
+            if seen.insert(node.to_string()) {
+                nodes.push(node);
+            }
+        }
+
+        nodes
     }
 }
 
@@ -134,6 +246,19 @@ mod tests {
         hash_ring.remove_node(&test_node);
     }
 
+    #[test]
+    fn test_add_weighted_node(){
+        let mut hash_ring = Ring::new(3);
+        assert_eq!(hash_ring.num_replicas, 3);
+
+        let test_node = TestNode{host_name: "Skynet", ip_address: "192.168.1.1", port: 42};
+        hash_ring.add_weighted_node(&test_node, 2);
+        assert_eq!(hash_ring.ring.len(), 6);
+
+        hash_ring.remove_node(&test_node);
+        assert!(hash_ring.ring.is_empty());
+    }
+
     #[test]
     fn test_get_node(){
         let mut hash_ring = Ring::new(3);
@@ -148,6 +273,20 @@ mod tests {
         assert_eq!(my_node.unwrap().port, test_node.port);
     }
 
+    #[test]
+    fn test_get_node_for(){
+        let mut hash_ring = Ring::new(3);
+        assert_eq!(hash_ring.num_replicas, 3);
+
+        let test_node = TestNode{host_name: "Skynet", ip_address: "192.168.1.1", port: 42};
+        hash_ring.add_node(&test_node);
+        let my_node = hash_ring.get_node_for(&"user:42");
+
+        assert_eq!(my_node.unwrap().host_name, test_node.host_name);
+        assert_eq!(my_node.unwrap().ip_address, test_node.ip_address);
+        assert_eq!(my_node.unwrap().port, test_node.port);
+    }
+
     #[test]
     fn test_add_nodes(){
         let mut hash_ring = Ring::new(3);
@@ -192,4 +331,47 @@ mod tests {
 
         assert!(hash_ring.ring.is_empty());
     }
+
+    #[test]
+    fn test_get_nodes(){
+        let mut hash_ring = Ring::new(3);
+        assert_eq!(hash_ring.num_replicas, 3);
+
+        let test_node1 = TestNode{host_name: "Skynet", ip_address: "192.168.1.1", port: 42};
+        let test_node2 = TestNode{host_name: "Inferno", ip_address: "10.0.1.1", port: 666};
+        let test_node3 = TestNode{host_name: "Klimt", ip_address: "127.0.0.1", port: 1};
+
+        let v = vec![test_node1.clone(), test_node2.clone(), test_node3.clone()];
+        hash_ring.add_nodes(&v);
+
+        let nodes = hash_ring.get_nodes(hash(&"some-key".to_string()), 2);
+        assert_eq!(nodes.len(), 2);
+        assert_ne!(nodes[0].to_string(), nodes[1].to_string());
+    }
+
+    #[test]
+    fn test_add_node_report(){
+        let mut hash_ring = Ring::new(3);
+        assert_eq!(hash_ring.num_replicas, 3);
+
+        let test_node = TestNode{host_name: "Skynet", ip_address: "192.168.1.1", port: 42};
+        let ranges = hash_ring.add_node_report(&test_node);
+
+        assert_eq!(ranges.len(), 3);
+    }
+
+    #[test]
+    fn test_remove_node_report(){
+        let mut hash_ring = Ring::new(3);
+        assert_eq!(hash_ring.num_replicas, 3);
+
+        let test_node1 = TestNode{host_name: "Skynet", ip_address: "192.168.1.1", port: 42};
+        let test_node2 = TestNode{host_name: "Inferno", ip_address: "10.0.1.1", port: 666};
+
+        hash_ring.add_node(&test_node1);
+        hash_ring.add_node(&test_node2);
+        let ranges = hash_ring.remove_node_report(&test_node1);
+
+        assert_eq!(ranges.len(), 3);
+    }
 }